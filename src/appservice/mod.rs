@@ -0,0 +1,118 @@
+//! Loading of Application Service registration files.
+//!
+//! A registration file is YAML describing one appservice: its `id`, the
+//! `as_token`/`hs_token` pair used to authenticate, and the namespaces of
+//! user/room/alias ids it owns. This module only concerns itself with
+//! reading and parsing those files; the `Store` is responsible for indexing
+//! the result for namespace lookups.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::models::appservice::Registration;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse(serde_yaml::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "could not read registration file: {}", e),
+            LoadError::Parse(e) => write!(f, "could not parse registration file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Reads and parses a single appservice registration file.
+pub fn load_registration(path: impl AsRef<Path>) -> Result<Registration, LoadError> {
+    let contents = fs::read_to_string(path).map_err(LoadError::Io)?;
+    serde_yaml::from_str(&contents).map_err(LoadError::Parse)
+}
+
+/// Reads and parses every registration file in `paths`, in order.
+pub fn load_registrations<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<Registration>, LoadError> {
+    paths.iter().map(load_registration).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    /// Writes `contents` to a fresh, uniquely-named file under the system
+    /// temp dir and returns its path, so concurrently-running tests don't
+    /// clobber each other.
+    fn write_temp_yaml(contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "maelstrom-test-registration-{}-{}.yaml",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst),
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_registration_parses_yaml_file() {
+        let path = write_temp_yaml(
+            r#"
+id: irc-bridge
+as_token: as_secret_token
+hs_token: hs_secret_token
+namespaces:
+  users:
+    - regex: "_irc_.*"
+      exclusive: true
+"#,
+        );
+
+        let registration = load_registration(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(registration.id, "irc-bridge");
+        assert_eq!(registration.as_token, "as_secret_token");
+        assert_eq!(registration.hs_token, "hs_secret_token");
+        assert_eq!(registration.namespaces.users.len(), 1);
+        assert_eq!(registration.namespaces.users[0].regex, "_irc_.*");
+        assert!(registration.namespaces.users[0].exclusive);
+    }
+
+    #[test]
+    fn test_load_registration_missing_file_is_io_error() {
+        let err = load_registration("/nonexistent/registration.yaml").unwrap_err();
+
+        assert!(matches!(err, LoadError::Io(_)));
+    }
+
+    #[test]
+    fn test_load_registration_invalid_yaml_is_parse_error() {
+        let path = write_temp_yaml("not: [valid");
+
+        let err = load_registration(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(err, LoadError::Parse(_)));
+    }
+
+    #[test]
+    fn test_load_registrations_parses_multiple_files_in_order() {
+        let first = write_temp_yaml("id: irc-bridge\nas_token: a\nhs_token: h\n");
+        let second = write_temp_yaml("id: slack-bridge\nas_token: a2\nhs_token: h2\n");
+
+        let registrations = load_registrations(&[&first, &second]).unwrap();
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+
+        assert_eq!(registrations.len(), 2);
+        assert_eq!(registrations[0].id, "irc-bridge");
+        assert_eq!(registrations[1].id, "slack-bridge");
+    }
+}
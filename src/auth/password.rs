@@ -0,0 +1,76 @@
+//! Password hashing for registration/login credentials.
+//!
+//! Hashes are produced by [`Algorithm`], a salted KDF wrapper whose output
+//! string embeds the algorithm, its cost, and a per-hash random salt (bcrypt's
+//! standard `$2b$<cost>$<salt><hash>` encoding). Keeping the algorithm behind
+//! an enum, rather than hard-coding bcrypt at every call site, means a future
+//! algorithm can be added and `needs_rehash` can flag hashes produced with
+//! weaker parameters than today's default.
+
+use bcrypt::{BcryptError, DEFAULT_COST};
+
+#[derive(Debug, Clone, Copy)]
+enum Algorithm {
+    Bcrypt { cost: u32 },
+}
+
+impl Algorithm {
+    fn current() -> Self {
+        Algorithm::Bcrypt { cost: DEFAULT_COST }
+    }
+
+    fn hash(self, password: &str) -> Result<String, BcryptError> {
+        match self {
+            Algorithm::Bcrypt { cost } => bcrypt::hash(password, cost),
+        }
+    }
+}
+
+/// Hashes `password` with a fresh random salt, returning a self-describing
+/// string suitable for storage.
+///
+/// `password` is attacker-controlled (it comes straight from the request
+/// body), so a hashing failure is reported to the caller instead of panicking.
+pub fn hash(password: &str) -> Result<String, BcryptError> {
+    Algorithm::current().hash(password)
+}
+
+/// Checks `password` against a hash produced by [`hash`].
+pub fn verify(password: &str, hash: &str) -> bool {
+    bcrypt::verify(password, hash).unwrap_or(false)
+}
+
+/// Whether `hash` was produced with weaker parameters than [`Algorithm::current`]
+/// would use today, so a successful login can trigger a rehash.
+pub fn needs_rehash(hash: &str) -> bool {
+    match bcrypt::get_cost(hash) {
+        Ok(cost) => cost < DEFAULT_COST,
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_accepts_the_hashed_password() {
+        let hashed = hash("s3kr1t").unwrap();
+
+        assert!(verify("s3kr1t", &hashed));
+    }
+
+    #[test]
+    fn test_verify_rejects_the_wrong_password() {
+        let hashed = hash("s3kr1t").unwrap();
+
+        assert!(!verify("not-it", &hashed));
+    }
+
+    #[test]
+    fn test_needs_rehash_is_false_for_a_fresh_hash() {
+        let hashed = hash("s3kr1t").unwrap();
+
+        assert!(!needs_rehash(&hashed));
+    }
+}
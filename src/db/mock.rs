@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+
+use super::{Store, StoreError};
+use crate::models::appservice::AppserviceMatch;
+use crate::models::registration::{UiaaFlow, UiaaSession};
+
+/// An in-memory, fully-stubbed [`Store`] used by handler tests.
+///
+/// Each method's behaviour is driven by a `..._resp` field: set it to `Some(Ok(_))`
+/// or `Some(Err(_))` to control what the call returns, or leave it `None` to get a
+/// sensible default (and, for UIAA bookkeeping, actually track state across calls
+/// so multi-request flows can be exercised).
+#[derive(Clone, Default)]
+pub struct MockStore {
+    pub check_username_exists_resp: Option<Result<bool, String>>,
+    pub create_user_resp: Option<Result<(), String>>,
+    pub create_guest_resp: Option<Result<(), String>>,
+    pub create_access_token_resp: Option<Result<String, String>>,
+    pub find_exclusive_namespace_resp: Option<Result<Option<String>, String>>,
+    pub appservice_for_token_resp: Option<Result<Option<AppserviceMatch>, String>>,
+    sessions: std::sync::Arc<std::sync::Mutex<Vec<UiaaSession>>>,
+}
+
+impl MockStore {
+    pub fn new() -> Self {
+        MockStore::default()
+    }
+}
+
+#[async_trait]
+impl Store for MockStore {
+    fn get_type(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn check_username_exists(&self, _username: &str) -> Result<bool, StoreError> {
+        match self.check_username_exists_resp.clone() {
+            Some(resp) => resp.map_err(StoreError),
+            None => Ok(false),
+        }
+    }
+
+    async fn create_uiaa_session(
+        &self,
+        flows: Vec<Vec<String>>,
+        username: Option<&str>,
+    ) -> Result<UiaaSession, StoreError> {
+        let session = UiaaSession {
+            session: format!("mock-session-{}", self.sessions.lock().unwrap().len()),
+            flows: flows.into_iter().map(|stages| UiaaFlow { stages }).collect(),
+            completed: Vec::new(),
+            params: serde_json::json!({}),
+            username: username.map(str::to_string),
+        };
+        self.sessions.lock().unwrap().push(session.clone());
+        Ok(session)
+    }
+
+    async fn complete_uiaa_stage(
+        &self,
+        session: &str,
+        stage: &str,
+    ) -> Result<Option<UiaaSession>, StoreError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let found = match sessions.iter_mut().find(|s| s.session == session) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        found.completed.push(stage.to_string());
+        Ok(Some(found.clone()))
+    }
+
+    async fn delete_uiaa_session(&self, session: &str) -> Result<(), StoreError> {
+        self.sessions.lock().unwrap().retain(|s| s.session != session);
+        Ok(())
+    }
+
+    async fn create_user(
+        &self,
+        _localpart: &str,
+        _password_hash: &str,
+        _device_id: &str,
+    ) -> Result<(), StoreError> {
+        match self.create_user_resp.clone() {
+            Some(resp) => resp.map_err(StoreError),
+            None => Ok(()),
+        }
+    }
+
+    async fn create_guest(
+        &self,
+        _localpart: &str,
+        _device_id: &str,
+        _initial_device_display_name: Option<&str>,
+    ) -> Result<(), StoreError> {
+        match self.create_guest_resp.clone() {
+            Some(resp) => resp.map_err(StoreError),
+            None => Ok(()),
+        }
+    }
+
+    async fn create_access_token(
+        &self,
+        localpart: &str,
+        device_id: &str,
+    ) -> Result<String, StoreError> {
+        match self.create_access_token_resp.clone() {
+            Some(resp) => resp.map_err(StoreError),
+            None => Ok(format!("mock-token-{}-{}", localpart, device_id)),
+        }
+    }
+
+    async fn find_exclusive_namespace(&self, _username: &str) -> Result<Option<String>, StoreError> {
+        match self.find_exclusive_namespace_resp.clone() {
+            Some(resp) => resp.map_err(StoreError),
+            None => Ok(None),
+        }
+    }
+
+    async fn appservice_for_token(
+        &self,
+        _as_token: &str,
+        _username: &str,
+    ) -> Result<Option<AppserviceMatch>, StoreError> {
+        match self.appservice_for_token_resp.clone() {
+            Some(resp) => resp.map_err(StoreError),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+pub mod mock;
+
+use async_trait::async_trait;
+use std::fmt;
+
+use crate::models::appservice::AppserviceMatch;
+use crate::models::registration::UiaaSession;
+
+/// Error type returned by every [`Store`] method.
+///
+/// Kept as an opaque, displayable wrapper so handlers can turn any storage
+/// failure into a `M_UNKNOWN` `MatrixError` via `ResultExt::unknown` without
+/// the `Store` trait leaking a concrete backend error type.
+#[derive(Debug)]
+pub struct StoreError(pub String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<E: std::error::Error> From<E> for StoreError {
+    fn from(e: E) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+/// Abstraction over the homeserver's persistent state.
+///
+/// Handlers are generic over `Store` so they can run against a real backend in
+/// production and against [`mock::MockStore`] in tests.
+#[async_trait]
+pub trait Store: Clone + Send + Sync + 'static {
+    /// A short, human-readable label for the backing implementation (e.g. `"sled"`,
+    /// `"mock"`), used for diagnostics.
+    fn get_type(&self) -> &'static str;
+
+    async fn check_username_exists(&self, username: &str) -> Result<bool, StoreError>;
+
+    /// Starts a new User-Interactive Authentication session advertising `flows`,
+    /// persisting it so later requests can resume it by `session` id.
+    ///
+    /// `username` is bound to the session when known, so a later stage
+    /// completion can be checked against the registration it was opened for
+    /// rather than blindly trusted.
+    async fn create_uiaa_session(
+        &self,
+        flows: Vec<Vec<String>>,
+        username: Option<&str>,
+    ) -> Result<UiaaSession, StoreError>;
+
+    /// Records that `stage` has been completed for `session` and returns the
+    /// updated session, or `None` if `session` doesn't exist.
+    async fn complete_uiaa_stage(
+        &self,
+        session: &str,
+        stage: &str,
+    ) -> Result<Option<UiaaSession>, StoreError>;
+
+    /// Deletes a UIAA session, e.g. once it has been consumed by a successful
+    /// registration so it can't be replayed to mint further accounts.
+    async fn delete_uiaa_session(&self, session: &str) -> Result<(), StoreError>;
+
+    /// Creates a new user account with the given localpart, hashed password
+    /// and device, returning nothing on success; the caller is expected to
+    /// mint the access token.
+    async fn create_user(
+        &self,
+        localpart: &str,
+        password_hash: &str,
+        device_id: &str,
+    ) -> Result<(), StoreError>;
+
+    /// Creates a limited-permission guest account under a server-generated
+    /// `localpart`/`device_id` pair.
+    async fn create_guest(
+        &self,
+        localpart: &str,
+        device_id: &str,
+        initial_device_display_name: Option<&str>,
+    ) -> Result<(), StoreError>;
+
+    /// Mints and persists a new access token bound to `(localpart, device_id)`.
+    async fn create_access_token(
+        &self,
+        localpart: &str,
+        device_id: &str,
+    ) -> Result<String, StoreError>;
+
+    /// Returns the id of the application service whose *exclusive* user
+    /// namespace matches `username`, if any.
+    async fn find_exclusive_namespace(&self, username: &str) -> Result<Option<String>, StoreError>;
+
+    /// Looks up the application service registered under `as_token`, and
+    /// reports whether `username` falls inside one of its user namespaces
+    /// (exclusive or shared).
+    async fn appservice_for_token(
+        &self,
+        as_token: &str,
+        username: &str,
+    ) -> Result<Option<AppserviceMatch>, StoreError>;
+}
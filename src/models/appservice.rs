@@ -0,0 +1,37 @@
+use serde::Deserialize;
+
+/// An Application Service registration, as loaded from its YAML registration
+/// file (see the `appservice` module for the loader).
+#[derive(Debug, Clone, Deserialize)]
+pub struct Registration {
+    pub id: String,
+    pub as_token: String,
+    pub hs_token: String,
+    #[serde(default)]
+    pub namespaces: Namespaces,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Namespaces {
+    #[serde(default)]
+    pub users: Vec<Namespace>,
+}
+
+/// A single namespace entry: a regex over full user ids/localparts plus
+/// whether the appservice exclusively owns anything it matches.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Namespace {
+    pub regex: String,
+    #[serde(default)]
+    pub exclusive: bool,
+}
+
+/// The result of looking up an appservice by its `as_token`, scoped to a
+/// particular username the caller is trying to act as.
+#[derive(Debug, Clone)]
+pub struct AppserviceMatch {
+    pub id: String,
+    /// Whether `username` falls inside one of this appservice's registered
+    /// user namespaces.
+    pub namespace_matches: bool,
+}
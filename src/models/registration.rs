@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+/// Query parameters accepted by `GET /_matrix/client/r0/register/available`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AvailableParams {
+    pub username: String,
+}
+
+/// Query parameters accepted by `POST /_matrix/client/r0/register`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequestParams {
+    #[serde(default)]
+    pub kind: RegistrationKind,
+
+    /// The `as_token` of an application service, present when it is
+    /// registering a virtual user inside one of its namespaces.
+    pub access_token: Option<String>,
+}
+
+/// The two account flavours the register endpoint can create.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationKind {
+    User,
+    Guest,
+}
+
+impl Default for RegistrationKind {
+    fn default() -> Self {
+        RegistrationKind::User
+    }
+}
+
+/// Body of `POST /_matrix/client/r0/register`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Request {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub device_id: Option<String>,
+    pub initial_device_display_name: Option<String>,
+    pub auth: Option<AuthData>,
+
+    /// Populated from the `kind` query parameter before the handler inspects it;
+    /// not part of the JSON body itself.
+    #[serde(skip, default)]
+    pub kind: RegistrationKind,
+}
+
+/// The `auth` field of a UIAA-authenticated request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthData {
+    pub session: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+}
+
+/// The `m.login.dummy` stage, which accepts any request and requires no
+/// additional credentials. Other stages can be added to the flows that
+/// `post_register` advertises as they are implemented.
+pub const STAGE_DUMMY: &str = "m.login.dummy";
+
+/// One acceptable sequence of stages a client can complete to satisfy UIAA.
+#[derive(Debug, Clone, Serialize)]
+pub struct UiaaFlow {
+    pub stages: Vec<String>,
+}
+
+/// Server-side state for an in-progress User-Interactive Authentication
+/// session, as tracked by the `Store`.
+#[derive(Debug, Clone, Serialize)]
+pub struct UiaaSession {
+    pub session: String,
+    pub flows: Vec<UiaaFlow>,
+    pub completed: Vec<String>,
+    #[serde(default)]
+    pub params: serde_json::Value,
+
+    /// The username this session was opened for, if known at creation time.
+    /// Not part of the wire format: a completed stage is only honoured for
+    /// the registration it was bound to, so one session can't be replayed
+    /// with a different username to mint unrelated accounts.
+    #[serde(skip)]
+    pub username: Option<String>,
+}
+
+impl UiaaSession {
+    /// Whether `completed` satisfies every stage of at least one advertised flow.
+    pub fn is_complete(&self) -> bool {
+        self.flows
+            .iter()
+            .any(|flow| flow.stages.iter().all(|stage| self.completed.contains(stage)))
+    }
+}
+
+/// The maximum length, in bytes, of a full Matrix user id (`@localpart:server_name`).
+const MAX_USER_ID_LEN: usize = 255;
+
+/// Why a proposed localpart can't be used as-is for this server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsernameError {
+    /// Contains characters outside the localpart grammar `[a-z0-9._=/-]+`.
+    InvalidGrammar,
+    /// `@localpart:server_name` would exceed the 255-byte MXID limit.
+    TooLong,
+}
+
+/// Validates that `username` is fit to be used as a Matrix user id localpart
+/// on a server named `server_name`.
+///
+/// Shared by `get_available` and `post_register` so both endpoints agree on
+/// exactly which usernames are acceptable.
+pub fn validate_localpart(username: &str, server_name: &str) -> Result<(), UsernameError> {
+    if username.is_empty()
+        || !username
+            .bytes()
+            .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'.' | b'_' | b'=' | b'-' | b'/'))
+    {
+        return Err(UsernameError::InvalidGrammar);
+    }
+
+    // "@" + localpart + ":" + server_name
+    if 2 + username.len() + server_name.len() > MAX_USER_ID_LEN {
+        return Err(UsernameError::TooLong);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_localpart_accepts_conforming_username() {
+        assert_eq!(validate_localpart("a.valid_user-id=1/2", "example.com"), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_localpart_rejects_invalid_grammar() {
+        assert_eq!(
+            validate_localpart("T@ken", "example.com"),
+            Err(UsernameError::InvalidGrammar)
+        );
+    }
+
+    #[test]
+    fn test_validate_localpart_rejects_mxid_over_255_chars() {
+        let username = "a".repeat(250);
+
+        assert_eq!(
+            validate_localpart(&username, "example.com"),
+            Err(UsernameError::TooLong)
+        );
+    }
+}
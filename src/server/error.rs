@@ -0,0 +1,80 @@
+use actix_web::{http, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt;
+
+/// A Matrix `errcode`, as defined by the standard error response format.
+///
+/// Modelled as a newtype over the wire string rather than a plain enum so that
+/// servers can return codes this crate doesn't know about yet without a breaking
+/// change, while still giving call sites named constants for the common cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ErrorCode(&'static str);
+
+impl ErrorCode {
+    pub const UNKNOWN: ErrorCode = ErrorCode("M_UNKNOWN");
+    pub const FORBIDDEN: ErrorCode = ErrorCode("M_FORBIDDEN");
+    pub const UNRECOGNIZED: ErrorCode = ErrorCode("M_UNRECOGNIZED");
+    pub const MISSING_PARAM: ErrorCode = ErrorCode("M_MISSING_PARAM");
+    pub const INVALID_USERNAME: ErrorCode = ErrorCode("M_INVALID_USERNAME");
+    pub const USER_IN_USE: ErrorCode = ErrorCode("M_USER_IN_USE");
+    pub const EXCLUSIVE: ErrorCode = ErrorCode("M_EXCLUSIVE");
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A Matrix-shaped error: an HTTP status plus the `{errcode, error}` JSON body
+/// the spec requires on every failed request.
+#[derive(Debug)]
+pub struct MatrixError {
+    status: http::StatusCode,
+    errcode: ErrorCode,
+    error: String,
+}
+
+impl MatrixError {
+    pub fn new(status: http::StatusCode, errcode: ErrorCode, error: impl Into<String>) -> Self {
+        MatrixError {
+            status,
+            errcode,
+            error: error.into(),
+        }
+    }
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.errcode, self.error)
+    }
+}
+
+impl ResponseError for MatrixError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(serde_json::json!({
+            "errcode": self.errcode.as_str(),
+            "error": self.error,
+        }))
+    }
+}
+
+/// Collapses a storage-layer (or other infrastructure) error into a `M_UNKNOWN`
+/// `MatrixError`, so handlers can bubble up `Store` failures with `?` instead of
+/// hand-writing a `map_err` at every call site.
+pub trait ResultExt<T> {
+    fn unknown(self) -> Result<T, MatrixError>;
+}
+
+impl<T, E: fmt::Display> ResultExt<T> for Result<T, E> {
+    fn unknown(self) -> Result<T, MatrixError> {
+        self.map_err(|e| {
+            MatrixError::new(http::StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::UNKNOWN, e.to_string())
+        })
+    }
+}
@@ -5,9 +5,22 @@ use actix_web::{
 };
 use serde_json::json;
 
+use crate::auth::password;
 use crate::server::error::{ErrorCode, MatrixError, ResultExt};
+use crate::util::random_string;
 use crate::{db::Store, models::registration as model};
 
+const DEVICE_ID_LEN: usize = 10;
+const DEVICE_ID_ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+const GUEST_LOCALPART_LEN: usize = 12;
+const GUEST_LOCALPART_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+fn generate_device_id() -> String {
+    random_string(DEVICE_ID_LEN, DEVICE_ID_ALPHABET)
+}
+
 /// Checks to see if a username is available, and valid, for the server.
 ///
 /// The server should check to ensure that, at the time of the request, the username
@@ -24,15 +37,18 @@ pub async fn get_available<T: Store>(
     params: Query<model::AvailableParams>,
     storage: Data<T>,
 ) -> Result<HttpResponse, MatrixError> {
-    // TODO: !!!Validate Username:
-    // M_INVALID_USERNAME : The desired username is not a valid user name.
-    // TODO: M_EXCLUSIVE : The desired username is in the exclusive namespace claimed by an application service.
+    model::validate_localpart(&params.username, &server_name()).map_err(username_error)?;
 
-    if !model::is_username_valid(&params.username) {
+    if storage
+        .find_exclusive_namespace(&params.username)
+        .await
+        .unknown()?
+        .is_some()
+    {
         Err(MatrixError::new(
             http::StatusCode::BAD_REQUEST,
-            ErrorCode::INVALID_USERNAME,
-            "The desired username is not a valid user name.",
+            ErrorCode::EXCLUSIVE,
+            "The desired username is in the exclusive namespace claimed by an application service.",
         ))?
     }
 
@@ -93,9 +109,280 @@ pub async fn post_register<T: Store>(
     storage: Data<T>,
 ) -> Result<HttpResponse, MatrixError> {
     req.kind = params.kind.clone();
-    println!("{}", storage.get_type());
 
-    unimplemented!()
+    if req.kind == model::RegistrationKind::Guest {
+        return register_guest(&storage, &req).await;
+    }
+
+    if req.kind == model::RegistrationKind::User {
+        if let Some(as_token) = params.access_token.clone() {
+            return register_appservice_user(&storage, &req, &as_token).await;
+        }
+    }
+
+    let session_id = match req.auth.as_ref().and_then(|auth| auth.session.clone()) {
+        Some(session_id) => session_id,
+        // No `auth` yet: open a new UIAA session and tell the client which
+        // flows will satisfy it.
+        None => {
+            let session = storage
+                .create_uiaa_session(
+                    vec![vec![model::STAGE_DUMMY.to_string()]],
+                    req.username.as_deref(),
+                )
+                .await
+                .unknown()?;
+
+            return Ok(HttpResponse::Unauthorized().json(json!({
+                "session": session.session,
+                "flows": session.flows,
+                "params": session.params,
+            })));
+        }
+    };
+
+    let stage = req
+        .auth
+        .as_ref()
+        .and_then(|auth| auth.kind.clone())
+        .ok_or_else(|| missing_param("auth.type"))?;
+
+    match stage.as_str() {
+        model::STAGE_DUMMY => {}
+        _ => Err(MatrixError::new(
+            http::StatusCode::BAD_REQUEST,
+            ErrorCode::UNRECOGNIZED,
+            "Unrecognized auth stage",
+        ))?,
+    }
+
+    let session = storage
+        .complete_uiaa_stage(&session_id, &stage)
+        .await
+        .unknown()?
+        .ok_or_else(unknown_session)?;
+
+    // A session is only valid for the registration it was opened for, so a
+    // session already spent on one username can't be replayed with another
+    // to mint an unrelated account.
+    if let Some(bound) = &session.username {
+        if Some(bound.as_str()) != req.username.as_deref() {
+            Err(unknown_session())?
+        }
+    }
+
+    if !session.is_complete() {
+        return Ok(HttpResponse::Unauthorized().json(json!({
+            "session": session.session,
+            "flows": session.flows,
+            "completed": session.completed,
+            "params": session.params,
+        })));
+    }
+
+    let username = req.username.clone().ok_or_else(|| missing_param("username"))?;
+    model::validate_localpart(&username, &server_name()).map_err(username_error)?;
+    reject_exclusive_namespace(&storage, &username).await?;
+    reject_if_taken(&storage, &username).await?;
+    let password = req.password.clone().ok_or_else(|| missing_param("password"))?;
+    let device_id = req
+        .device_id
+        .clone()
+        .unwrap_or_else(generate_device_id);
+
+    let password_hash = password::hash(&password).unknown()?;
+    storage
+        .create_user(&username, &password_hash, &device_id)
+        .await
+        .unknown()?;
+
+    // The session has now minted an account; delete it so it can't be
+    // replayed to mint another one.
+    storage.delete_uiaa_session(&session_id).await.unknown()?;
+
+    let access_token = storage
+        .create_access_token(&username, &device_id)
+        .await
+        .unknown()?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "user_id": format!("@{}:{}", username, server_name()),
+        "access_token": access_token,
+        "device_id": device_id,
+    })))
+}
+
+/// Registers a guest account.
+///
+/// Per the endpoint's own doc comment, every body parameter except
+/// `initial_device_display_name` is ignored: the server picks both the
+/// localpart and the `device_id`.
+async fn register_guest<T: Store>(
+    storage: &Data<T>,
+    req: &model::Request,
+) -> Result<HttpResponse, MatrixError> {
+    let localpart = random_string(GUEST_LOCALPART_LEN, GUEST_LOCALPART_ALPHABET);
+    let device_id = generate_device_id();
+
+    storage
+        .create_guest(
+            &localpart,
+            &device_id,
+            req.initial_device_display_name.as_deref(),
+        )
+        .await
+        .unknown()?;
+    let access_token = storage
+        .create_access_token(&localpart, &device_id)
+        .await
+        .unknown()?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "user_id": format!("@{}:{}", localpart, server_name()),
+        "access_token": access_token,
+        "device_id": device_id,
+    })))
+}
+
+/// Registers a virtual user on behalf of an application service.
+///
+/// An application service authenticates with its `as_token` instead of going
+/// through UIAA, and may only register usernames that fall inside one of its
+/// own declared user namespaces.
+async fn register_appservice_user<T: Store>(
+    storage: &Data<T>,
+    req: &model::Request,
+    as_token: &str,
+) -> Result<HttpResponse, MatrixError> {
+    let username = req.username.clone().ok_or_else(|| missing_param("username"))?;
+    model::validate_localpart(&username, &server_name()).map_err(username_error)?;
+
+    let appservice = storage
+        .appservice_for_token(as_token, &username)
+        .await
+        .unknown()?
+        .ok_or_else(|| {
+            MatrixError::new(
+                http::StatusCode::FORBIDDEN,
+                ErrorCode::FORBIDDEN,
+                "Unrecognized access_token",
+            )
+        })?;
+
+    if !appservice.namespace_matches {
+        Err(MatrixError::new(
+            http::StatusCode::BAD_REQUEST,
+            ErrorCode::EXCLUSIVE,
+            "Username is outside of the application service's namespace",
+        ))?
+    }
+
+    reject_if_taken(storage, &username).await?;
+
+    let device_id = req
+        .device_id
+        .clone()
+        .unwrap_or_else(generate_device_id);
+
+    // Virtual users authenticate via the appservice's as_token, not a password;
+    // when the request carries no password, an unguessable one is generated,
+    // hashed and stored so create_user's contract (every account has a
+    // password_hash) holds uniformly.
+    let password = req
+        .password
+        .clone()
+        .unwrap_or_else(|| random_string(32, DEVICE_ID_ALPHABET));
+    let password_hash = password::hash(&password).unknown()?;
+
+    storage
+        .create_user(&username, &password_hash, &device_id)
+        .await
+        .unknown()?;
+    let access_token = storage
+        .create_access_token(&username, &device_id)
+        .await
+        .unknown()?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "user_id": format!("@{}:{}", username, server_name()),
+        "access_token": access_token,
+        "device_id": device_id,
+    })))
+}
+
+/// Rejects `username` with `M_EXCLUSIVE` if it falls inside an application
+/// service's exclusive namespace.
+///
+/// `get_available` performs this same check, but it's purely advisory:
+/// clients aren't required to call it before registering, so the real
+/// registration path must enforce it too.
+async fn reject_exclusive_namespace<T: Store>(
+    storage: &Data<T>,
+    username: &str,
+) -> Result<(), MatrixError> {
+    if storage
+        .find_exclusive_namespace(username)
+        .await
+        .unknown()?
+        .is_some()
+    {
+        Err(MatrixError::new(
+            http::StatusCode::BAD_REQUEST,
+            ErrorCode::EXCLUSIVE,
+            "The desired username is in the exclusive namespace claimed by an application service.",
+        ))?
+    }
+
+    Ok(())
+}
+
+/// Rejects `username` with `M_USER_IN_USE` if it's already registered.
+///
+/// `get_available` reports this same conflict, but clients aren't required
+/// to call it first, so both registration paths must check again
+/// immediately before `create_user` to avoid surfacing a generic
+/// `M_UNKNOWN` for what's actually a spec-mandated 400.
+async fn reject_if_taken<T: Store>(storage: &Data<T>, username: &str) -> Result<(), MatrixError> {
+    let exists = storage.check_username_exists(username).await.unknown()?;
+
+    if exists {
+        Err(MatrixError::new(
+            http::StatusCode::BAD_REQUEST,
+            ErrorCode::USER_IN_USE,
+            "Desired user ID is already taken.",
+        ))?
+    }
+
+    Ok(())
+}
+
+fn unknown_session() -> MatrixError {
+    MatrixError::new(http::StatusCode::FORBIDDEN, ErrorCode::FORBIDDEN, "Unknown session")
+}
+
+fn missing_param(name: &str) -> MatrixError {
+    MatrixError::new(
+        http::StatusCode::BAD_REQUEST,
+        ErrorCode::MISSING_PARAM,
+        format!("{} is required", name),
+    )
+}
+
+fn username_error(err: model::UsernameError) -> MatrixError {
+    let message = match err {
+        model::UsernameError::InvalidGrammar => "The desired username is not a valid user name.",
+        model::UsernameError::TooLong => "The resulting user ID is too long.",
+    };
+
+    MatrixError::new(http::StatusCode::BAD_REQUEST, ErrorCode::INVALID_USERNAME, message)
+}
+
+/// The domain clients see in `user_id`s minted by this server.
+///
+/// TODO: source this from the homeserver config once one exists, rather than
+/// an environment variable.
+fn server_name() -> String {
+    std::env::var("SERVER_NAME").unwrap_or_else(|_| "localhost".to_string())
 }
 
 #[cfg(test)]
@@ -169,4 +456,388 @@ mod tests {
 
         assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
     }
+
+    #[actix_rt::test]
+    async fn test_get_available_username_exclusive_namespace() {
+        crate::init_config_from_file(".env-test");
+
+        let mut test_db = MockStore::new();
+        test_db.check_username_exists_resp = Some(Ok(false));
+        test_db.find_exclusive_namespace_resp = Some(Ok(Some("irc-bridge".to_string())));
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::get().to(get_available::<MockStore>)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/?username=_irc_bot")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_without_auth_returns_uiaa_session() {
+        crate::init_config_from_file(".env-test");
+
+        let test_db = MockStore::new();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({"username": "alice"}))
+            .to_request();
+        let mut resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = test::read_body_json(&mut resp).await;
+        assert!(body["session"].is_string());
+        assert_eq!(body["flows"][0]["stages"][0], json!(model::STAGE_DUMMY));
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_dummy_stage_completes_registration() {
+        crate::init_config_from_file(".env-test");
+
+        let test_db = MockStore::new();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+
+        let first = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({"username": "alice"}))
+            .to_request();
+        let mut first_resp = test::call_service(&mut app, first).await;
+        let body: serde_json::Value = test::read_body_json(&mut first_resp).await;
+        let session = body["session"].as_str().unwrap().to_string();
+
+        let second = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({
+                "username": "alice",
+                "password": "correct horse battery staple",
+                "auth": {"session": session, "type": model::STAGE_DUMMY},
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, second).await;
+
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_username_exclusive_namespace_rejected() {
+        crate::init_config_from_file(".env-test");
+
+        let mut test_db = MockStore::new();
+        test_db.find_exclusive_namespace_resp = Some(Ok(Some("irc-bridge".to_string())));
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+
+        let first = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({"username": "_irc_bot"}))
+            .to_request();
+        let mut first_resp = test::call_service(&mut app, first).await;
+        let body: serde_json::Value = test::read_body_json(&mut first_resp).await;
+        let session = body["session"].as_str().unwrap().to_string();
+
+        let second = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({
+                "username": "_irc_bot",
+                "password": "correct horse battery staple",
+                "auth": {"session": session, "type": model::STAGE_DUMMY},
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, second).await;
+
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_username_taken_rejected() {
+        crate::init_config_from_file(".env-test");
+
+        let mut test_db = MockStore::new();
+        test_db.check_username_exists_resp = Some(Ok(true));
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+
+        let first = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({"username": "alice"}))
+            .to_request();
+        let mut first_resp = test::call_service(&mut app, first).await;
+        let body: serde_json::Value = test::read_body_json(&mut first_resp).await;
+        let session = body["session"].as_str().unwrap().to_string();
+
+        let second = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({
+                "username": "alice",
+                "password": "correct horse battery staple",
+                "auth": {"session": session, "type": model::STAGE_DUMMY},
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, second).await;
+
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_uiaa_session_cannot_be_replayed_for_another_username() {
+        crate::init_config_from_file(".env-test");
+
+        let test_db = MockStore::new();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+
+        let first = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({"username": "alice"}))
+            .to_request();
+        let mut first_resp = test::call_service(&mut app, first).await;
+        let body: serde_json::Value = test::read_body_json(&mut first_resp).await;
+        let session = body["session"].as_str().unwrap().to_string();
+
+        let complete = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({
+                "username": "alice",
+                "password": "correct horse battery staple",
+                "auth": {"session": session, "type": model::STAGE_DUMMY},
+            }))
+            .to_request();
+        let first_complete = test::call_service(&mut app, complete).await;
+        assert!(first_complete.status().is_success());
+
+        let replay = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({
+                "username": "bob",
+                "password": "another password",
+                "auth": {"session": session, "type": model::STAGE_DUMMY},
+            }))
+            .to_request();
+        let replay_resp = test::call_service(&mut app, replay).await;
+
+        assert_eq!(replay_resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_uiaa_session_rejects_username_mismatch() {
+        crate::init_config_from_file(".env-test");
+
+        let test_db = MockStore::new();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+
+        let first = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({"username": "alice"}))
+            .to_request();
+        let mut first_resp = test::call_service(&mut app, first).await;
+        let body: serde_json::Value = test::read_body_json(&mut first_resp).await;
+        let session = body["session"].as_str().unwrap().to_string();
+
+        let second = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({
+                "username": "mallory",
+                "password": "correct horse battery staple",
+                "auth": {"session": session, "type": model::STAGE_DUMMY},
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, second).await;
+
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_unrecognized_stage_rejected() {
+        crate::init_config_from_file(".env-test");
+
+        let test_db = MockStore::new();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+
+        let first = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({"username": "alice"}))
+            .to_request();
+        let mut first_resp = test::call_service(&mut app, first).await;
+        let body: serde_json::Value = test::read_body_json(&mut first_resp).await;
+        let session = body["session"].as_str().unwrap().to_string();
+
+        let second = test::TestRequest::post()
+            .uri("/")
+            .set_json(&json!({
+                "username": "alice",
+                "auth": {"session": session, "type": "m.login.password"},
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, second).await;
+
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_guest_bypasses_uiaa() {
+        crate::init_config_from_file(".env-test");
+
+        let test_db = MockStore::new();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/?kind=guest")
+            .set_json(&json!({"initial_device_display_name": "phone"}))
+            .to_request();
+        let mut resp = test::call_service(&mut app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: serde_json::Value = test::read_body_json(&mut resp).await;
+        assert!(body["user_id"].is_string());
+        assert!(body["device_id"].is_string());
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_appservice_user_bypasses_uiaa() {
+        crate::init_config_from_file(".env-test");
+
+        let mut test_db = MockStore::new();
+        test_db.appservice_for_token_resp = Some(Ok(Some(crate::models::appservice::AppserviceMatch {
+            id: "irc-bridge".to_string(),
+            namespace_matches: true,
+        })));
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/?kind=user&access_token=as_secret_token")
+            .set_json(&json!({"username": "_irc_bot"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_appservice_user_invalid_grammar_rejected() {
+        crate::init_config_from_file(".env-test");
+
+        let test_db = MockStore::new();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/?kind=user&access_token=as_secret_token")
+            .set_json(&json!({"username": "T@ken"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_appservice_user_already_taken_rejected() {
+        crate::init_config_from_file(".env-test");
+
+        let mut test_db = MockStore::new();
+        test_db.appservice_for_token_resp = Some(Ok(Some(crate::models::appservice::AppserviceMatch {
+            id: "irc-bridge".to_string(),
+            namespace_matches: true,
+        })));
+        test_db.check_username_exists_resp = Some(Ok(true));
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/?kind=user&access_token=as_secret_token")
+            .set_json(&json!({"username": "_irc_bot"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_post_register_appservice_user_outside_namespace_rejected() {
+        crate::init_config_from_file(".env-test");
+
+        let mut test_db = MockStore::new();
+        test_db.appservice_for_token_resp = Some(Ok(Some(crate::models::appservice::AppserviceMatch {
+            id: "irc-bridge".to_string(),
+            namespace_matches: false,
+        })));
+
+        let mut app = test::init_service(
+            App::new()
+                .data(test_db)
+                .route("/", web::post().to(post_register::<MockStore>)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/?kind=user&access_token=as_secret_token")
+            .set_json(&json!({"username": "alice"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
 }
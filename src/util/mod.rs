@@ -0,0 +1,13 @@
+use rand::Rng;
+
+/// Generates a random string of `len` characters, each drawn uniformly from
+/// `alphabet`.
+///
+/// Used wherever the server needs to pick an identifier on the client's
+/// behalf, such as auto-generated `device_id`s and guest localparts.
+pub fn random_string(len: usize, alphabet: &[u8]) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len)
+        .map(|_| alphabet[rng.gen_range(0, alphabet.len())] as char)
+        .collect()
+}